@@ -0,0 +1,347 @@
+// SPDX-FileCopyrightText: 2021 Andrew 'glyph' Reid
+//
+// SPDX-License-Identifier: LGPL-3.0-only
+
+//! Message-key derivation and feed-chain validation.
+//!
+//! Appending to a feed requires knowing the `previous` message key, which this crate previously
+//! had no way to compute. [`message_key`] fills that gap: SHA-256 over the full Bencoded
+//! `BendyMsg`, formatted as the `%<base64>.bbmsg-v1` sigil. [`validate_chain`] builds on it to
+//! walk a sequence of messages and check that each one legitimately extends the feed — sequence,
+//! previous pointer, author, and signature all have to line up — stopping at the first
+//! inconsistency with a typed error that names the offending sequence number.
+
+use crate::{encode, verify, Msg, GENESIS_PREVIOUS};
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+const MSG_SUFFIX: &str = ".bbmsg-v1";
+
+/// A feed-chain inconsistency, naming the sequence number at which it was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainError {
+    /// `sequence` did not increment by exactly one from the prior message.
+    SequenceGap { at: i32, expected: i32, found: i32 },
+    /// The prior message's `sequence` was already `i32::MAX`, so no message can validly follow it
+    /// — computing the expected next sequence would overflow.
+    SequenceOverflow { at: i32 },
+    /// `previous` did not match the computed key of the prior message (or the genesis sentinel,
+    /// for a first message).
+    PreviousMismatch {
+        at: i32,
+        expected: String,
+        found: String,
+    },
+    /// `author` changed partway through the feed.
+    AuthorChanged {
+        at: i32,
+        expected: String,
+        found: String,
+    },
+    /// The message's ed25519 signature did not verify.
+    InvalidSignature { at: i32 },
+    /// Signature verification, or computing the prior message's key, could not even be attempted
+    /// — e.g. a malformed `previous` sigil — distinct from a cleanly-failed check.
+    VerificationFailed { at: i32, reason: String },
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::SequenceGap { at, expected, found } => write!(
+                f,
+                "sequence {at}: expected sequence {expected}, found {found}"
+            ),
+            ChainError::SequenceOverflow { at } => write!(
+                f,
+                "sequence {at}: prior sequence is i32::MAX, no valid next sequence exists"
+            ),
+            ChainError::PreviousMismatch { at, expected, found } => write!(
+                f,
+                "sequence {at}: expected previous \"{expected}\", found \"{found}\""
+            ),
+            ChainError::AuthorChanged { at, expected, found } => write!(
+                f,
+                "sequence {at}: author changed from \"{expected}\" to \"{found}\""
+            ),
+            ChainError::InvalidSignature { at } => {
+                write!(f, "sequence {at}: signature does not verify")
+            }
+            ChainError::VerificationFailed { at, reason } => {
+                write!(f, "sequence {at}: could not verify message: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+/// Compute the `%<base64>.bbmsg-v1` message key of an already-Bencoded `BendyMsg`, i.e. the value
+/// the *next* message in the feed must carry as its `previous` field.
+pub fn message_key(bendy_bytes: &[u8]) -> Result<String> {
+    let hash = Sha256::digest(bendy_bytes);
+    Ok(format!("%{}{}", STANDARD.encode(hash), MSG_SUFFIX))
+}
+
+/// Check `msg`'s signature, distinguishing a clean `Ok(false)` (genuinely bad signature) from an
+/// `Err` (couldn't even attempt verification, e.g. a malformed sigil) so callers don't conflate
+/// the two under the same error variant.
+fn verify_at(msg: &Msg) -> Result<(), ChainError> {
+    match verify(msg) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(ChainError::InvalidSignature { at: msg.sequence }),
+        Err(e) => Err(ChainError::VerificationFailed {
+            at: msg.sequence,
+            reason: e.to_string(),
+        }),
+    }
+}
+
+/// Walk `msgs` and check that each adjacent pair forms a valid feed chain: `sequence` increments
+/// by exactly one, `previous` equals the computed [`message_key`] of the prior message (or the
+/// genesis sentinel, for a first message at the start of the slice), `author` stays constant, and
+/// each message's signature verifies. Returns the first [`ChainError`] encountered, naming the
+/// sequence number at which the chain broke, so callers can quarantine a bad replication batch.
+pub fn validate_chain(msgs: &[Msg]) -> Result<(), ChainError> {
+    if let Some(first) = msgs.first() {
+        if first.sequence == 1 && first.previous != GENESIS_PREVIOUS {
+            return Err(ChainError::PreviousMismatch {
+                at: first.sequence,
+                expected: GENESIS_PREVIOUS.to_string(),
+                found: first.previous.clone(),
+            });
+        }
+        verify_at(first)?;
+    }
+
+    for pair in msgs.windows(2) {
+        let (prev, curr) = (&pair[0], &pair[1]);
+
+        let expected_sequence = prev
+            .sequence
+            .checked_add(1)
+            .ok_or(ChainError::SequenceOverflow { at: curr.sequence })?;
+
+        if curr.sequence != expected_sequence {
+            return Err(ChainError::SequenceGap {
+                at: curr.sequence,
+                expected: expected_sequence,
+                found: curr.sequence,
+            });
+        }
+
+        if curr.author != prev.author {
+            return Err(ChainError::AuthorChanged {
+                at: curr.sequence,
+                expected: prev.author.clone(),
+                found: curr.author.clone(),
+            });
+        }
+
+        let prev_bytes = encode(prev).map_err(|e| ChainError::VerificationFailed {
+            at: prev.sequence,
+            reason: e.to_string(),
+        })?;
+        let expected_previous =
+            message_key(&prev_bytes).map_err(|e| ChainError::VerificationFailed {
+                at: prev.sequence,
+                reason: e.to_string(),
+            })?;
+        if curr.previous != expected_previous {
+            return Err(ChainError::PreviousMismatch {
+                at: curr.sequence,
+                expected: expected_previous,
+                found: curr.previous.clone(),
+            });
+        }
+
+        verify_at(curr)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{BOX2, FEED, MSG};
+    use crate::{sign, Content, Msg};
+    use ed25519_dalek::SigningKey;
+
+    const FEED_SUFFIX: &str = ".bbfeed-v1";
+    const SIG_SUFFIX: &str = ".sig.ed25519";
+
+    fn signed_genesis(secret_key: [u8; 32]) -> Msg {
+        let signing_key = SigningKey::from_bytes(&secret_key);
+        let author = format!(
+            "@{}{}",
+            STANDARD.encode(signing_key.verifying_key().to_bytes()),
+            FEED_SUFFIX
+        );
+        let unsigned = Msg {
+            previous: GENESIS_PREVIOUS.to_string(),
+            author,
+            sequence: 1,
+            timestamp: 1,
+            content: Content::Private(BOX2.to_string()),
+            signature: String::new(),
+        };
+        sign(&unsigned, &secret_key).unwrap()
+    }
+
+    fn signed_next(prev: &Msg, secret_key: [u8; 32]) -> Msg {
+        let prev_bytes = encode(prev).unwrap();
+        let unsigned = Msg {
+            previous: message_key(&prev_bytes).unwrap(),
+            author: prev.author.clone(),
+            sequence: prev.sequence + 1,
+            timestamp: prev.timestamp + 1,
+            content: Content::Private(BOX2.to_string()),
+            signature: String::new(),
+        };
+        sign(&unsigned, &secret_key).unwrap()
+    }
+
+    #[test]
+    fn message_key_hashes_the_bendy_bytes() {
+        let bytes = b"arbitrary bendy bytes".to_vec();
+        let hash = Sha256::digest(&bytes);
+        assert_eq!(
+            message_key(&bytes).unwrap(),
+            format!("%{}{}", STANDARD.encode(hash), MSG_SUFFIX)
+        );
+    }
+
+    #[test]
+    fn validate_chain_accepts_a_genesis_message() {
+        let genesis = signed_genesis([1u8; 32]);
+        assert_eq!(validate_chain(&[genesis]), Ok(()));
+    }
+
+    #[test]
+    fn validate_chain_accepts_a_linked_pair() {
+        let secret_key = [2u8; 32];
+        let genesis = signed_genesis(secret_key);
+        let next = signed_next(&genesis, secret_key);
+        assert_eq!(validate_chain(&[genesis, next]), Ok(()));
+    }
+
+    #[test]
+    fn validate_chain_rejects_non_genesis_previous_on_first_message() {
+        let mut genesis = signed_genesis([3u8; 32]);
+        genesis.previous = MSG.to_string();
+        match validate_chain(&[genesis]) {
+            Err(ChainError::PreviousMismatch { at: 1, .. }) => {}
+            other => panic!("expected PreviousMismatch, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn validate_chain_rejects_a_sequence_gap() {
+        let secret_key = [4u8; 32];
+        let genesis = signed_genesis(secret_key);
+        let mut next = signed_next(&genesis, secret_key);
+        next.sequence = 3;
+        assert_eq!(
+            validate_chain(&[genesis, next]),
+            Err(ChainError::SequenceGap {
+                at: 3,
+                expected: 2,
+                found: 3
+            })
+        );
+    }
+
+    #[test]
+    fn validate_chain_rejects_a_sequence_overflow_instead_of_panicking() {
+        let secret_key = [7u8; 32];
+        let signing_key = SigningKey::from_bytes(&secret_key);
+        let author = format!(
+            "@{}{}",
+            STANDARD.encode(signing_key.verifying_key().to_bytes()),
+            FEED_SUFFIX
+        );
+
+        // A prior message already at `i32::MAX` has no valid next sequence — `curr`'s actual
+        // fields don't matter, since the overflow must be caught before they're even compared.
+        let unsigned_prev = Msg {
+            previous: GENESIS_PREVIOUS.to_string(),
+            author: author.clone(),
+            sequence: i32::MAX,
+            timestamp: 1,
+            content: Content::Private(BOX2.to_string()),
+            signature: String::new(),
+        };
+        let prev = sign(&unsigned_prev, &secret_key).unwrap();
+
+        let unsigned_curr = Msg {
+            previous: MSG.to_string(),
+            author,
+            sequence: i32::MIN,
+            timestamp: 2,
+            content: Content::Private(BOX2.to_string()),
+            signature: String::new(),
+        };
+        let curr = sign(&unsigned_curr, &secret_key).unwrap();
+
+        assert_eq!(
+            validate_chain(&[prev, curr]),
+            Err(ChainError::SequenceOverflow { at: i32::MIN })
+        );
+    }
+
+    #[test]
+    fn validate_chain_rejects_a_changed_author() {
+        let secret_key = [5u8; 32];
+        let genesis = signed_genesis(secret_key);
+        let mut next = signed_next(&genesis, secret_key);
+        next.author = FEED.to_string();
+        match validate_chain(&[genesis, next]) {
+            Err(ChainError::AuthorChanged { at: 2, .. }) => {}
+            other => panic!("expected AuthorChanged, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn validate_chain_rejects_a_previous_mismatch() {
+        let secret_key = [6u8; 32];
+        let genesis = signed_genesis(secret_key);
+        let mut next = signed_next(&genesis, secret_key);
+        next.previous = MSG.to_string();
+        match validate_chain(&[genesis, next]) {
+            Err(ChainError::PreviousMismatch { at: 2, .. }) => {}
+            other => panic!("expected PreviousMismatch, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn validate_chain_rejects_an_invalid_signature() {
+        let secret_key = [8u8; 32];
+        let genesis = signed_genesis(secret_key);
+        let mut next = signed_next(&genesis, secret_key);
+        // Invalidates the signature without touching any field `validate_chain` checks first.
+        next.timestamp += 1;
+        assert_eq!(
+            validate_chain(&[genesis, next]),
+            Err(ChainError::InvalidSignature { at: 2 })
+        );
+    }
+
+    #[test]
+    fn validate_chain_reports_a_verification_failure_separately_from_a_bad_signature() {
+        let msg = Msg {
+            previous: "not a valid message sigil".to_string(),
+            author: format!("@{}{}", STANDARD.encode([0u8; 32]), FEED_SUFFIX),
+            sequence: 5,
+            timestamp: 1,
+            content: Content::Private(BOX2.to_string()),
+            signature: format!("{}{}", STANDARD.encode([0u8; 64]), SIG_SUFFIX),
+        };
+        match validate_chain(&[msg]) {
+            Err(ChainError::VerificationFailed { at: 5, .. }) => {}
+            other => panic!("expected VerificationFailed, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,194 @@
+// SPDX-FileCopyrightText: 2021 Andrew 'glyph' Reid
+//
+// SPDX-License-Identifier: LGPL-3.0-only
+
+//! Typed metafeed content variants.
+//!
+//! A metafeed tree is built out of several distinct operations, each with its own field set, not
+//! just the single "add a subfeed" shape. [`FeedContent`] dispatches on the `feed_type` string
+//! (the same discriminator the untyped predecessor of this module used) and deserializes straight
+//! into the matching struct, rejecting any `feed_type`/field combination it doesn't recognize.
+
+use serde::{Deserialize, Serialize};
+
+/// A single entry in a message's `tangles` map, identifying the root of a tangle and the tips it
+/// builds on — see the SSB tangle spec.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Tangle {
+    pub root: Option<String>,
+    pub previous: Vec<String>,
+}
+
+/// The tangles a metafeed content object is threaded into. Every operation in this module is
+/// threaded into the `metafeed` tangle.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Tangles {
+    pub metafeed: Tangle,
+}
+
+/// Announces that an already-existing feed is being added as a subfeed of `metafeed`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AddExisting {
+    pub feed_format: String,
+    pub subfeed: String,
+    pub metafeed: String,
+    pub tangles: Tangles,
+}
+
+/// Announces that a new subfeed, derived from `nonce`, is being added to `metafeed`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AddDerived {
+    pub feed_format: String,
+    pub subfeed: String,
+    pub metafeed: String,
+    pub nonce: String,
+    pub tangles: Tangles,
+}
+
+/// Revokes `subfeed`'s membership of `metafeed`. Carries no feed format, since a tombstoned
+/// subfeed is no longer being described, only retired.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tombstone {
+    pub subfeed: String,
+    pub metafeed: String,
+    pub nonce: String,
+    pub tangles: Tangles,
+}
+
+/// Announces the seed `metafeed`'s subfeeds are deterministically derived from, so a peer that
+/// only holds the metafeed's public identity can still reconstruct which keys belong to it.
+/// Unlike `AddExisting`/`AddDerived`/`Tombstone`, this doesn't describe a single subfeed — it's
+/// usually the first message of a metafeed, seeding the whole tree at once.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Seed {
+    pub metafeed: String,
+    pub seed: String,
+    pub tangles: Tangles,
+}
+
+/// Represents the typed metafeed operation payload carried by a `Content::Feed` message, keyed
+/// off the `feed_type` field of the underlying content object. Unknown `feed_type` values, or a
+/// `feed_type` whose fields don't match the variant it names, are rejected at deserialization
+/// time rather than silently decoded into the wrong shape.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "feed_type")]
+pub enum FeedContent {
+    #[serde(rename = "metafeed/add/existing")]
+    AddExisting(AddExisting),
+    #[serde(rename = "metafeed/add/derived")]
+    AddDerived(AddDerived),
+    #[serde(rename = "metafeed/tombstone")]
+    Tombstone(Tombstone),
+    #[serde(rename = "metafeed/seed")]
+    Seed(Seed),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tangles() -> Tangles {
+        Tangles {
+            metafeed: Tangle {
+                root: None,
+                previous: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn round_trips_add_existing() {
+        let content = FeedContent::AddExisting(AddExisting {
+            feed_format: "classic".to_string(),
+            subfeed: "@subfeed".to_string(),
+            metafeed: "@metafeed".to_string(),
+            tangles: tangles(),
+        });
+
+        let value = serde_json::to_value(&content).unwrap();
+        assert_eq!(value["feed_type"], json!("metafeed/add/existing"));
+        assert_eq!(
+            serde_json::from_value::<FeedContent>(value).unwrap(),
+            content
+        );
+    }
+
+    #[test]
+    fn round_trips_add_derived() {
+        let content = FeedContent::AddDerived(AddDerived {
+            feed_format: "classic".to_string(),
+            subfeed: "@subfeed".to_string(),
+            metafeed: "@metafeed".to_string(),
+            nonce: "nonce".to_string(),
+            tangles: tangles(),
+        });
+
+        let value = serde_json::to_value(&content).unwrap();
+        assert_eq!(value["feed_type"], json!("metafeed/add/derived"));
+        assert_eq!(
+            serde_json::from_value::<FeedContent>(value).unwrap(),
+            content
+        );
+    }
+
+    #[test]
+    fn round_trips_tombstone() {
+        let content = FeedContent::Tombstone(Tombstone {
+            subfeed: "@subfeed".to_string(),
+            metafeed: "@metafeed".to_string(),
+            nonce: "nonce".to_string(),
+            tangles: tangles(),
+        });
+
+        let value = serde_json::to_value(&content).unwrap();
+        assert_eq!(value["feed_type"], json!("metafeed/tombstone"));
+        assert_eq!(
+            serde_json::from_value::<FeedContent>(value).unwrap(),
+            content
+        );
+    }
+
+    #[test]
+    fn round_trips_seed() {
+        let content = FeedContent::Seed(Seed {
+            metafeed: "@metafeed".to_string(),
+            seed: "seed".to_string(),
+            tangles: tangles(),
+        });
+
+        let value = serde_json::to_value(&content).unwrap();
+        assert_eq!(value["feed_type"], json!("metafeed/seed"));
+        assert_eq!(
+            serde_json::from_value::<FeedContent>(value).unwrap(),
+            content
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_feed_type() {
+        let value = json!({
+            "feed_type": "metafeed/unknown",
+            "metafeed": "@metafeed",
+        });
+
+        assert!(serde_json::from_value::<FeedContent>(value).is_err());
+    }
+
+    #[test]
+    fn rejects_feed_type_with_mismatched_fields() {
+        // `metafeed/tombstone`'s fields without the `nonce` every `Tombstone` carries.
+        let value = json!({
+            "feed_type": "metafeed/tombstone",
+            "subfeed": "@subfeed",
+            "metafeed": "@metafeed",
+            "tangles": { "metafeed": { "root": null, "previous": [] } },
+        });
+
+        assert!(serde_json::from_value::<FeedContent>(value).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+// SPDX-FileCopyrightText: 2021 Andrew 'glyph' Reid
+//
+// SPDX-License-Identifier: LGPL-3.0-only
+
+//! Ed25519 signing and verification for Bendy Butt messages.
+//!
+//! Per the Bendy Butt spec, a signature covers the Bencoded bytes of the *payload tuple only*
+//! (`[author, sequence, previous, timestamp, content]` after BFE encoding) and never the
+//! `BendyMsg` as a whole, i.e. the signature field is excluded from its own preimage.
+
+use crate::{encode_payload, Msg};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+const FEED_SIGIL: char = '@';
+const FEED_SUFFIX: &str = ".bbfeed-v1";
+const SIG_SUFFIX: &str = ".sig.ed25519";
+
+/// Compute the exact bytes a Bendy Butt signature is made over: the BFE-encoded payload tuple,
+/// Bencode-serialized on its own, without the trailing signature field.
+fn payload_bytes(msg: &Msg) -> Result<Vec<u8>> {
+    let payload = encode_payload(msg)?;
+    Ok(bendy::serde::to_bytes(&payload)?)
+}
+
+/// Parse the `author` sigil (`@<base64>.bbfeed-v1`) into the verifying key it encodes.
+fn parse_author_key(author: &str) -> Result<VerifyingKey> {
+    let encoded = author
+        .strip_prefix(FEED_SIGIL)
+        .and_then(|rest| rest.strip_suffix(FEED_SUFFIX))
+        .ok_or_else(|| anyhow!("author is not a valid feed sigil: {}", author))?;
+
+    let key_bytes = STANDARD.decode(encoded)?;
+    let key_bytes: [u8; 32] = key_bytes
+        .try_into()
+        .map_err(|_| anyhow!("feed key is not 32 bytes"))?;
+
+    Ok(VerifyingKey::from_bytes(&key_bytes)?)
+}
+
+/// Parse the `signature` sigil (`<base64>.sig.ed25519`) into an ed25519 `Signature`.
+fn parse_signature(signature: &str) -> Result<Signature> {
+    let encoded = signature
+        .strip_suffix(SIG_SUFFIX)
+        .ok_or_else(|| anyhow!("signature is not a valid sig.ed25519 sigil: {}", signature))?;
+
+    let sig_bytes = STANDARD.decode(encoded)?;
+    let sig_bytes: [u8; 64] = sig_bytes
+        .try_into()
+        .map_err(|_| anyhow!("signature is not 64 bytes"))?;
+
+    Ok(Signature::from_bytes(&sig_bytes))
+}
+
+/// Sign the BFE-encoded payload tuple of `payload` with the given ed25519 secret key and return a
+/// copy of the message with its `signature` field populated. The secret key is expected to be the
+/// raw 32-byte ed25519 seed.
+pub fn sign(payload: &Msg, secret_key: &[u8]) -> Result<Msg> {
+    let secret_key: [u8; 32] = secret_key
+        .try_into()
+        .map_err(|_| anyhow!("secret key is not 32 bytes"))?;
+    let signing_key = SigningKey::from_bytes(&secret_key);
+
+    let bytes = payload_bytes(payload)?;
+    let signature: Signature = signing_key.sign(&bytes);
+
+    let mut signed = payload.clone();
+    signed.signature = format!("{}{}", STANDARD.encode(signature.to_bytes()), SIG_SUFFIX);
+
+    Ok(signed)
+}
+
+/// Verify that `msg.signature` is a valid ed25519 signature, by the key encoded in `msg.author`,
+/// over the BFE-encoded payload tuple of `msg`. Malformed keys or signatures are reported as
+/// `Ok(false)` rather than an error, so that callers can treat verification as a simple boolean
+/// check on untrusted input.
+pub fn verify(msg: &Msg) -> Result<bool> {
+    let verifying_key = match parse_author_key(&msg.author) {
+        Ok(key) => key,
+        Err(_) => return Ok(false),
+    };
+    let signature = match parse_signature(&msg.signature) {
+        Ok(sig) => sig,
+        Err(_) => return Ok(false),
+    };
+
+    let bytes = payload_bytes(msg)?;
+
+    Ok(verifying_key.verify(&bytes, &signature).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Content;
+
+    const FEED: &str = "@6CAxOI3f+LUOVrbAl0IemqiS7ATpQvr9Mdw9LC4+Uv0=.bbfeed-v1";
+    const MSG: &str = "%H3MlLmVPVgHU6rBSzautUBZibDttkI+cU4lAFUIM8Ag=.bbmsg-v1";
+
+    #[test]
+    fn sign_then_verify_round_trips() {
+        let secret_key = [7u8; 32];
+        let signing_key = SigningKey::from_bytes(&secret_key);
+        let verifying_key = signing_key.verifying_key();
+        let author = format!(
+            "@{}{}",
+            STANDARD.encode(verifying_key.to_bytes()),
+            FEED_SUFFIX
+        );
+
+        let unsigned = Msg {
+            previous: MSG.to_string(),
+            author,
+            sequence: 1,
+            timestamp: 1,
+            content: Content::Private("unused".to_string()),
+            signature: String::new(),
+        };
+
+        let signed = sign(&unsigned, &secret_key).unwrap();
+        assert!(verify(&signed).unwrap());
+    }
+
+    #[test]
+    fn verify_rejects_malformed_author() {
+        let msg = Msg {
+            previous: MSG.to_string(),
+            author: "not a feed sigil".to_string(),
+            sequence: 1,
+            timestamp: 1,
+            content: Content::Private("unused".to_string()),
+            signature: format!("{}{}", STANDARD.encode([0u8; 64]), SIG_SUFFIX),
+        };
+
+        assert!(!verify(&msg).unwrap());
+    }
+
+    #[test]
+    fn verify_rejects_tampered_payload() {
+        let secret_key = [9u8; 32];
+        let signing_key = SigningKey::from_bytes(&secret_key);
+        let verifying_key = signing_key.verifying_key();
+        let author = format!("{}{}{}", FEED_SIGIL, STANDARD.encode(verifying_key.to_bytes()), FEED_SUFFIX);
+
+        let unsigned = Msg {
+            previous: MSG.to_string(),
+            author,
+            sequence: 1,
+            timestamp: 1,
+            content: Content::Private("unused".to_string()),
+            signature: String::new(),
+        };
+
+        let mut signed = sign(&unsigned, &secret_key).unwrap();
+        signed.sequence = 2;
+        assert!(!verify(&signed).unwrap());
+    }
+
+    #[test]
+    fn parse_author_key_rejects_missing_sigil() {
+        assert!(parse_author_key(FEED.trim_start_matches(FEED_SIGIL)).is_err());
+    }
+}
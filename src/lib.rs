@@ -5,7 +5,28 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use ssb_bfe_rs::BfeValue;
+use ssb_bfe_rs::{data::MSG_FORMATS, BfeValue};
+
+mod chain;
+mod content;
+mod crypto;
+mod stream;
+#[cfg(test)]
+mod test_fixtures;
+mod untrusted;
+
+pub use chain::{message_key, validate_chain, ChainError};
+pub use content::{AddDerived, AddExisting, FeedContent, Seed, Tangle, Tangles, Tombstone};
+pub use crypto::{sign, verify};
+pub use stream::{decode_log, encode_log};
+pub use untrusted::{decode_untrusted, prototype, Prototype};
+
+/// Sentinel value of the `previous` field on a feed's first message. `ssb_bfe_rs` has no sigil for
+/// "no message yet" — a real message key always carries hash bytes after its type-format prefix —
+/// so [`encode_payload`]/[`decode_bendy_msg`] special-case this string on the way in and out,
+/// encoding/decoding it as the bare 2-byte `message`/`classic` type-format buffer (no hash data)
+/// rather than asking `ssb_bfe_rs::encode_msg`/`decode` to round-trip the literal text.
+pub(crate) const GENESIS_PREVIOUS: &str = "null";
 
 /* ENCODED TYPES */
 
@@ -28,7 +49,7 @@ pub enum BendyContent {
 /* DECODED TYPES */
 
 /// Represents a decoded Bendy Butt message with payload fields and signature.
-#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Msg {
     previous: String,
     author: String,
@@ -39,74 +60,85 @@ pub struct Msg {
 }
 
 /// Represents the content payload variants of a decoded Bendy Butt message.
-#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub enum Content {
     // encrypted message (box2)
     Private(String),
-    Feed(FeedData, String),
+    Feed(FeedContent, String),
 }
 
-/// Represents the message content payload feed data of a decoded Bendy Butt message.
-#[derive(Debug, PartialEq, Deserialize, Serialize)]
-pub struct FeedData {
-    feed_type: String,
-    subfeed: String,
-    metafeed: String,
-    nonce: String,
-}
-
-/// Take a message in the form of a `Msg` `struct`, encode the fields using the BFE encoding scheme
-/// (excluding `sequence` and `timestamp`), then encode the whole message with Bencode and return
-/// the bytes as a `Vec<u8>`.
-pub fn encode(msg: &Msg) -> Result<Vec<u8>> {
-    let content: BendyContent;
-    match &msg.content {
+/// BFE-encode the `author`, `previous` and `content` fields of a `Msg` and pack them, together
+/// with its plain `sequence` and `timestamp`, into a `BendyPayload`, leaving the `signature` untouched. Shared by [`encode`] and the
+/// [`crypto`] subsystem, since both need the exact bytes that a signature is computed over.
+pub(crate) fn encode_payload(msg: &Msg) -> Result<BendyPayload> {
+    let content = match &msg.content {
         Content::Private(msg) => {
             let encoded_msg = ssb_bfe_rs::encode_box(msg)?;
-            content = BendyContent::Private(BfeValue::Buffer(encoded_msg));
+            BendyContent::Private(BfeValue::Buffer(encoded_msg))
         }
         Content::Feed(data, sig) => {
             let encoded_data = ssb_bfe_rs::encode(&json!(data))?;
             let encoded_sig = ssb_bfe_rs::encode_sig(sig)?;
-            content = BendyContent::Feed(encoded_data, BfeValue::Buffer(encoded_sig));
+            BendyContent::Feed(encoded_data, BfeValue::Buffer(encoded_sig))
         }
-    }
+    };
 
-    let previous = BfeValue::Buffer(ssb_bfe_rs::encode_msg(&msg.previous)?);
+    let previous = if msg.previous == GENESIS_PREVIOUS {
+        BfeValue::Buffer(MSG_FORMATS["classic"].0.to_vec())
+    } else {
+        BfeValue::Buffer(ssb_bfe_rs::encode_msg(&msg.previous)?)
+    };
     let author = BfeValue::Buffer(ssb_bfe_rs::encode_feed(&msg.author)?);
-    let sequence = msg.sequence;
-    let timestamp = msg.timestamp;
+
+    Ok(BendyPayload(
+        author,
+        msg.sequence,
+        previous,
+        msg.timestamp,
+        content,
+    ))
+}
+
+/// Take a message in the form of a `Msg` `struct`, encode the fields using the BFE encoding scheme
+/// (excluding `sequence` and `timestamp`), then encode the whole message with Bencode and return
+/// the bytes as a `Vec<u8>`.
+pub fn encode(msg: &Msg) -> Result<Vec<u8>> {
+    let payload = encode_payload(msg)?;
     let signature = BfeValue::Buffer(ssb_bfe_rs::encode_sig(&msg.signature)?);
 
-    let payload = BendyPayload(author, sequence, previous, timestamp, content);
     let bendy_msg = BendyMsg(payload, signature);
     let bencoded_msg = bendy::serde::to_bytes(&bendy_msg)?;
 
     Ok(bencoded_msg)
 }
 
-/// Take a message in the form of a Bencoded byte vector, deserialize and decode the bytes to
-/// extract the message field data, then decode the BFE values and return a `Msg` `struct`.
-pub fn decode(bendy_msg: Vec<u8>) -> Result<Msg> {
-    let BendyMsg(payload, signature) = bendy::serde::from_bytes(&bendy_msg)?;
+/// Decode the BFE values out of an already-parsed `BendyMsg` and return a `Msg` `struct`. Shared
+/// by the trusted [`decode`] path and the [`untrusted::decode_untrusted`] path, which only differ
+/// in how much they check before reaching this point.
+pub(crate) fn decode_bendy_msg(bendy_msg: BendyMsg) -> Result<Msg> {
+    let BendyMsg(payload, signature) = bendy_msg;
     let BendyPayload(author, sequence, previous, timestamp, content_data) = payload;
 
-    let content;
-
-    match content_data {
+    let content = match content_data {
         BendyContent::Private(msg) => {
             let decoded_msg = serde_json::from_value(ssb_bfe_rs::decode(&msg)?)?;
-            content = Content::Private(decoded_msg);
+            Content::Private(decoded_msg)
         }
         BendyContent::Feed(data, sig) => {
-            let feed_data: FeedData = serde_json::from_value(ssb_bfe_rs::decode(&data)?)?;
+            let feed_content: FeedContent = serde_json::from_value(ssb_bfe_rs::decode(&data)?)?;
             let feed_sig = serde_json::from_value(ssb_bfe_rs::decode(&sig)?)?;
-            content = Content::Feed(feed_data, feed_sig)
+            Content::Feed(feed_content, feed_sig)
         }
-    }
+    };
+
+    let decoded_previous = ssb_bfe_rs::decode(&previous)?;
+    let previous = match decoded_previous {
+        serde_json::Value::Null => GENESIS_PREVIOUS.to_string(),
+        other => serde_json::from_value(other)?,
+    };
 
     let msg = Msg {
-        previous: serde_json::from_value(ssb_bfe_rs::decode(&previous)?)?,
+        previous,
         author: serde_json::from_value(ssb_bfe_rs::decode(&author)?)?,
         sequence,
         timestamp,
@@ -117,9 +149,18 @@ pub fn decode(bendy_msg: Vec<u8>) -> Result<Msg> {
     Ok(msg)
 }
 
+/// Take a message in the form of a Bencoded byte vector, deserialize and decode the bytes to
+/// extract the message field data, then decode the BFE values and return a `Msg` `struct`.
+pub fn decode(bendy_msg: Vec<u8>) -> Result<Msg> {
+    let bendy_msg = bendy::serde::from_bytes(&bendy_msg)?;
+    decode_bendy_msg(bendy_msg)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{decode, encode, Content, FeedData, Msg};
+    use crate::{
+        decode, encode, AddDerived, Content, FeedContent, Msg, Tangle, Tangles, GENESIS_PREVIOUS,
+    };
     use bendy::encoding::ToBencode;
 
     #[test]
@@ -154,18 +195,48 @@ mod tests {
         assert_eq!(msg, decoded_msg);
     }
 
+    #[test]
+    fn encode_then_decode_msg_with_genesis_previous() {
+        let content = Content::Private(BOX2.to_string());
+
+        let msg = Msg {
+            previous: GENESIS_PREVIOUS.to_string(),
+            author: FEED.to_string(),
+            sequence: 1,
+            timestamp: 1,
+            content,
+            signature: SIG.to_string(),
+        };
+
+        let encoded = encode(&msg);
+        assert!(encoded.is_ok());
+        let encoded_msg = encoded.unwrap();
+
+        let decoded = decode(encoded_msg);
+        assert!(decoded.is_ok());
+        let decoded_msg = decoded.unwrap();
+
+        assert_eq!(msg, decoded_msg);
+    }
+
     #[test]
     fn encode_then_decode_msg_with_feed_content() {
-        let feed_data = FeedData {
-            feed_type: "metafeed/add".to_string(),
+        let feed_content = FeedContent::AddDerived(AddDerived {
+            feed_format: "classic".to_string(),
             subfeed: FEED.to_string(),
             metafeed: FEED.to_string(),
             nonce: NONCE.to_string(),
-        };
+            tangles: Tangles {
+                metafeed: Tangle {
+                    root: None,
+                    previous: Vec::new(),
+                },
+            },
+        });
 
         let feed_sig = FEED_SIG.to_string();
 
-        let content = Content::Feed(feed_data, feed_sig);
+        let content = Content::Feed(feed_content, feed_sig);
 
         let msg = Msg {
             previous: MSG.to_string(),
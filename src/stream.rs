@@ -0,0 +1,144 @@
+// SPDX-FileCopyrightText: 2021 Andrew 'glyph' Reid
+//
+// SPDX-License-Identifier: LGPL-3.0-only
+
+//! Streaming encode/decode for whole feed logs.
+//!
+//! [`encode`]/[`decode`] work on one owned `Vec<u8>` per message, which is wasteful when ingesting
+//! a replicated log of thousands of messages. This module frames each message with a 4-byte
+//! big-endian length prefix instead, so a log can be read and written one message at a time
+//! without holding the whole thing in memory.
+
+use crate::{decode, encode, Msg};
+use anyhow::{anyhow, Result};
+use std::io::{Read, Write};
+
+const LENGTH_PREFIX_BYTES: usize = 4;
+
+/// Upper bound on a single framed message, well above anything this crate would legitimately
+/// produce. Rejects a corrupt or adversarial length prefix instead of acting on it.
+const MAX_FRAME_LEN: usize = 1024 * 1024;
+
+/// Encode each message in `msgs` and write it to `writer` as a 4-byte big-endian length prefix
+/// followed by the Bencoded message bytes.
+pub fn encode_log<W: Write>(msgs: impl IntoIterator<Item = Msg>, mut writer: W) -> Result<()> {
+    for msg in msgs {
+        let bytes = encode(&msg)?;
+        let len = u32::try_from(bytes.len())?;
+        writer.write_all(&len.to_be_bytes())?;
+        writer.write_all(&bytes)?;
+    }
+
+    Ok(())
+}
+
+/// Read length-delimited Bencoded messages off `reader` one at a time. A decode failure on one
+/// message is surfaced as an `Err` without losing the reader's place in the stream, so the rest
+/// of the log can still be read; a framing or I/O failure ends the stream, since the reader's
+/// position can no longer be trusted.
+pub fn decode_log<R: Read>(reader: R) -> impl Iterator<Item = Result<Msg>> {
+    LogDecoder { reader, done: false }
+}
+
+struct LogDecoder<R> {
+    reader: R,
+    done: bool,
+}
+
+impl<R: Read> Iterator for LogDecoder<R> {
+    type Item = Result<Msg>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut len_bytes = [0u8; LENGTH_PREFIX_BYTES];
+        match self.reader.read_exact(&mut len_bytes) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                self.done = true;
+                return None;
+            }
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e.into()));
+            }
+        }
+        let len = u32::from_be_bytes(len_bytes) as usize;
+        if len > MAX_FRAME_LEN {
+            self.done = true;
+            return Some(Err(anyhow!(
+                "frame length {len} exceeds the {MAX_FRAME_LEN}-byte maximum"
+            )));
+        }
+
+        let mut bytes = vec![0u8; len];
+        if let Err(e) = self.reader.read_exact(&mut bytes) {
+            self.done = true;
+            return Some(Err(e.into()));
+        }
+
+        Some(decode(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{BOX2, FEED, MSG, SIG};
+    use crate::Content;
+
+    fn sample_msg(sequence: i32) -> Msg {
+        Msg {
+            previous: MSG.to_string(),
+            author: FEED.to_string(),
+            sequence,
+            timestamp: 1,
+            content: Content::Private(BOX2.to_string()),
+            signature: SIG.to_string(),
+        }
+    }
+
+    #[test]
+    fn encode_then_decode_log_round_trips() {
+        let msgs = vec![sample_msg(2), sample_msg(3), sample_msg(4)];
+
+        let mut buffer = Vec::new();
+        encode_log(msgs.clone(), &mut buffer).unwrap();
+
+        let decoded: Result<Vec<Msg>> = decode_log(buffer.as_slice()).collect();
+        assert_eq!(msgs, decoded.unwrap());
+    }
+
+    #[test]
+    fn decode_log_surfaces_a_bad_frame_without_losing_its_place() {
+        let msgs = [sample_msg(2), sample_msg(3)];
+
+        let mut buffer = Vec::new();
+        encode_log(vec![msgs[0].clone()], &mut buffer).unwrap();
+
+        // Splice in a well-formed frame whose contents aren't a valid Bencoded message.
+        let garbage = b"not bencode".to_vec();
+        buffer.extend_from_slice(&(garbage.len() as u32).to_be_bytes());
+        buffer.extend_from_slice(&garbage);
+
+        encode_log(vec![msgs[1].clone()], &mut buffer).unwrap();
+
+        let decoded: Vec<Result<Msg>> = decode_log(buffer.as_slice()).collect();
+        assert_eq!(decoded.len(), 3);
+        assert!(decoded[0].is_ok());
+        assert!(decoded[1].is_err());
+        assert!(decoded[2].is_ok());
+    }
+
+    #[test]
+    fn decode_log_rejects_an_oversized_length_prefix_without_allocating() {
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&u32::MAX.to_be_bytes());
+
+        let decoded: Vec<Result<Msg>> = decode_log(buffer.as_slice()).collect();
+        assert_eq!(decoded.len(), 1);
+        assert!(decoded[0].is_err());
+    }
+}
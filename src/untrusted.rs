@@ -0,0 +1,267 @@
+// SPDX-FileCopyrightText: 2021 Andrew 'glyph' Reid
+//
+// SPDX-License-Identifier: LGPL-3.0-only
+
+//! Untrusted decoding path.
+//!
+//! [`crate::decode`] is the fast, trusted path: it assumes the input is well-formed and lets
+//! `serde`/`bendy` errors bubble straight up. That's fine for a message this process already
+//! authenticated and stored itself, but not for bytes a peer just handed over the wire.
+//! [`decode_untrusted`] checks the Bencode structure first — outer shape, field counts, buffer
+//! lengths — so a malformed or adversarial message is rejected with a clear reason instead of
+//! failing deep inside `serde` or panicking on an out-of-range index.
+
+use crate::{decode_bendy_msg, BendyContent, BendyMsg, BendyPayload, Msg};
+use anyhow::{anyhow, bail, Result};
+use bendy::decoding::{Decoder, Object};
+use ssb_bfe_rs::{
+    data::{FEED_FORMATS, MSG_FORMATS, SIGNATURE_FORMATS},
+    BfeValue,
+};
+
+/// BFE buffer length (2-byte type-format prefix + key/hash/signature bytes) for each buffer this
+/// crate decodes.
+const FEED_BUFFER_LEN: usize = 34; // prefix + 32-byte ed25519 public key
+const MSG_BUFFER_LEN: usize = 34; // prefix + 32-byte sha256 hash
+const SIG_BUFFER_LEN: usize = 66; // prefix + 64-byte ed25519 signature
+
+/// Length of the genesis sentinel `previous` produces: the bare 2-byte type-format prefix with no
+/// trailing hash bytes at all, since a feed's first message has no prior message to point to.
+const GENESIS_PREVIOUS_BUFFER_LEN: usize = 2;
+
+/// The lowest sequence number a feed's first message may carry.
+const MIN_SEQUENCE: i32 = 1;
+
+/// Shape of a Bencode value's outermost token, without decoding what it contains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prototype {
+    /// A list (or dict, counted by key/value pairs) with this many top-level elements.
+    List(usize),
+    /// A byte string or integer token, this many bytes long.
+    Data(usize),
+    /// No object present at all.
+    Null,
+}
+
+/// Peek at the top-level shape of a Bencoded value without fully deserializing it. Useful for
+/// triaging a stream of mixed messages before committing to a full decode of any one of them.
+pub fn prototype(bytes: &[u8]) -> Result<Prototype> {
+    let mut decoder = Decoder::new(bytes);
+    let proto = match decoder.next_object().map_err(|e| anyhow!(e.to_string()))? {
+        Some(Object::List(mut list)) => {
+            let mut len = 0;
+            while list
+                .next_object()
+                .map_err(|e| anyhow!(e.to_string()))?
+                .is_some()
+            {
+                len += 1;
+            }
+            Prototype::List(len)
+        }
+        Some(Object::Dict(mut dict)) => {
+            let mut len = 0;
+            while dict
+                .next_pair()
+                .map_err(|e| anyhow!(e.to_string()))?
+                .is_some()
+            {
+                len += 1;
+            }
+            Prototype::List(len)
+        }
+        Some(Object::Bytes(data)) => Prototype::Data(data.len()),
+        Some(Object::Integer(digits)) => Prototype::Data(digits.len()),
+        None => Prototype::Null,
+    };
+    Ok(proto)
+}
+
+/// Check that a BFE value is a buffer of exactly `expected_len` bytes carrying `expected_prefix`
+/// as its type-format tag. Length alone isn't enough to tell buffers of different BFE types
+/// apart — e.g. a classic `feed` and a classic `message` buffer are both 34 bytes — so a swapped
+/// buffer of the wrong type but the right length would otherwise sail through unchecked.
+fn expect_buffer(
+    value: &BfeValue,
+    expected_prefix: &[u8],
+    expected_len: usize,
+    field: &str,
+) -> Result<()> {
+    match value {
+        BfeValue::Buffer(bytes) if bytes.len() != expected_len => bail!(
+            "{} buffer is {} bytes long, expected {}",
+            field,
+            bytes.len(),
+            expected_len
+        ),
+        BfeValue::Buffer(bytes) if !bytes.starts_with(expected_prefix) => bail!(
+            "{} has type-format prefix {:?}, expected {:?}",
+            field,
+            &bytes[..expected_prefix.len()],
+            expected_prefix
+        ),
+        BfeValue::Buffer(_) => Ok(()),
+        _ => bail!("{} is not a BFE buffer", field),
+    }
+}
+
+/// Take a message in the form of a Bencoded byte vector that has NOT yet been authenticated,
+/// validate its structure, then decode it the same way [`crate::decode`] does.
+///
+/// Before any BFE decoding is attempted this confirms: the outer value is a 2-element
+/// `[payload, signature]` list; the payload is itself a 5-element list; `sequence` and
+/// `timestamp` fall within plausible ranges; and the `author`, `previous` and `signature` buffers,
+/// plus a feed content's signature buffer, have the byte length and type-format prefix their field
+/// requires. The private content buffer and a feed content's data are not checked here. This
+/// keeps adversarial input from reaching `serde`/BFE decoding in a shape those layers don't expect.
+pub fn decode_untrusted(bytes: &[u8]) -> Result<Msg> {
+    match prototype(bytes)? {
+        Prototype::List(2) => {}
+        Prototype::List(len) => {
+            bail!("expected a 2-element [payload, signature] list, found {len} elements")
+        }
+        other => bail!("expected a Bencode list, found {other:?}"),
+    }
+
+    let bendy_msg: BendyMsg = bendy::serde::from_bytes(bytes)?;
+    let BendyMsg(ref payload, ref signature) = bendy_msg;
+    let BendyPayload(ref author, sequence, ref previous, timestamp, ref content) = *payload;
+
+    if sequence < MIN_SEQUENCE {
+        bail!("sequence {sequence} is out of range");
+    }
+    if timestamp < 0 {
+        bail!("timestamp {timestamp} is out of range");
+    }
+
+    expect_buffer(author, FEED_FORMATS["classic"].0, FEED_BUFFER_LEN, "author")?;
+    expect_buffer(
+        signature,
+        SIGNATURE_FORMATS["msg-ed25519"].0,
+        SIG_BUFFER_LEN,
+        "signature",
+    )?;
+    if sequence > MIN_SEQUENCE {
+        expect_buffer(previous, MSG_FORMATS["classic"].0, MSG_BUFFER_LEN, "previous")?;
+    } else {
+        // The first message of a feed has no prior message, so `previous` carries the bare
+        // genesis sentinel — the type-format prefix alone, not a full hash-sized buffer.
+        expect_buffer(
+            previous,
+            MSG_FORMATS["classic"].0,
+            GENESIS_PREVIOUS_BUFFER_LEN,
+            "previous",
+        )?;
+    }
+    if let BendyContent::Feed(_, content_sig) = content {
+        expect_buffer(
+            content_sig,
+            SIGNATURE_FORMATS["msg-ed25519"].0,
+            SIG_BUFFER_LEN,
+            "content signature",
+        )?;
+    }
+
+    decode_bendy_msg(bendy_msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{BOX2, FEED, MSG, SIG};
+    use crate::{encode, Content, Msg};
+
+    #[test]
+    fn prototype_peeks_top_level_shape() {
+        let list = bendy::serde::to_bytes(&vec!["hello", "world"]).unwrap();
+        assert_eq!(prototype(&list).unwrap(), Prototype::List(2));
+    }
+
+    #[test]
+    fn decode_untrusted_accepts_well_formed_message() {
+        let msg = Msg {
+            previous: MSG.to_string(),
+            author: FEED.to_string(),
+            sequence: 2,
+            timestamp: 1,
+            content: Content::Private(BOX2.to_string()),
+            signature: SIG.to_string(),
+        };
+
+        let encoded = encode(&msg).unwrap();
+        let decoded = decode_untrusted(&encoded).unwrap();
+        assert_eq!(msg, decoded);
+    }
+
+    #[test]
+    fn decode_untrusted_rejects_bad_outer_shape() {
+        let not_a_message =
+            bendy::serde::to_bytes(&vec!["only", "one", "field", "too", "many"]).unwrap();
+        assert!(decode_untrusted(&not_a_message).is_err());
+    }
+
+    #[test]
+    fn decode_untrusted_rejects_sequence_below_one() {
+        let msg = Msg {
+            previous: MSG.to_string(),
+            author: FEED.to_string(),
+            sequence: 0,
+            timestamp: 1,
+            content: Content::Private(BOX2.to_string()),
+            signature: SIG.to_string(),
+        };
+
+        // `encode` happily serializes this, but the first message of a feed can't have
+        // sequence 0 — `decode_untrusted` should catch it structurally.
+        let encoded = encode(&msg).unwrap();
+        assert!(decode_untrusted(&encoded).is_err());
+    }
+
+    #[test]
+    fn decode_untrusted_rejects_malformed_genesis_previous() {
+        let msg = Msg {
+            previous: crate::GENESIS_PREVIOUS.to_string(),
+            author: FEED.to_string(),
+            sequence: 1,
+            timestamp: 1,
+            content: Content::Private(BOX2.to_string()),
+            signature: SIG.to_string(),
+        };
+
+        let encoded = encode(&msg).unwrap();
+        let mut bendy_msg: BendyMsg = bendy::serde::from_bytes(&encoded).unwrap();
+
+        // Tack a trailing hash byte onto the bare genesis sentinel — a shape `encode_payload`
+        // never produces, which the 2-byte genesis length check must reject for sequence 1.
+        if let BfeValue::Buffer(bytes) = &mut bendy_msg.0 .2 {
+            bytes.push(0);
+        }
+        let tampered = bendy::serde::to_bytes(&bendy_msg).unwrap();
+
+        assert!(decode_untrusted(&tampered).is_err());
+    }
+
+    #[test]
+    fn decode_untrusted_rejects_author_with_wrong_bfe_type() {
+        let msg = Msg {
+            previous: MSG.to_string(),
+            author: FEED.to_string(),
+            sequence: 2,
+            timestamp: 1,
+            content: Content::Private(BOX2.to_string()),
+            signature: SIG.to_string(),
+        };
+
+        let encoded = encode(&msg).unwrap();
+        let mut bendy_msg: BendyMsg = bendy::serde::from_bytes(&encoded).unwrap();
+
+        // Same buffer length as a classic `feed` sigil, but the wrong BFE type-format prefix —
+        // this should only trip the prefix check, not the length check.
+        if let BfeValue::Buffer(bytes) = &mut bendy_msg.0 .0 {
+            bytes[..2].copy_from_slice(MSG_FORMATS["classic"].0);
+        }
+        let tampered = bendy::serde::to_bytes(&bendy_msg).unwrap();
+
+        assert!(decode_untrusted(&tampered).is_err());
+    }
+}